@@ -0,0 +1,62 @@
+use crate::errors::DocError;
+use std::convert::TryFrom;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, serde::Serialize, serde::Deserialize)]
+pub struct RevId(pub i64);
+
+impl std::convert::From<RevId> for i64 {
+    fn from(rev_id: RevId) -> i64 { rev_id.0 }
+}
+
+impl std::convert::From<i64> for RevId {
+    fn from(rev_id: i64) -> RevId { RevId(rev_id) }
+}
+
+impl std::fmt::Display for RevId {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result { write!(f, "{}", self.0) }
+}
+
+/// A single OT change sent between the client and the server. `base_rev_id`
+/// is the revision the delta was composed against; `rev_id` is assigned once
+/// the revision is accepted (by the server, or locally before an ack arrives).
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct Revision {
+    pub doc_id: String,
+    pub base_rev_id: i64,
+    pub rev_id: i64,
+    pub delta_data: Vec<u8>,
+}
+
+impl Revision {
+    pub fn new(doc_id: &str, base_rev_id: i64, rev_id: i64, delta_data: Vec<u8>) -> Self {
+        Self {
+            doc_id: doc_id.to_owned(),
+            base_rev_id,
+            rev_id,
+            delta_data,
+        }
+    }
+}
+
+impl TryFrom<Vec<u8>> for Revision {
+    type Error = DocError;
+
+    fn try_from(bytes: Vec<u8>) -> Result<Self, Self::Error> {
+        bincode::deserialize(&bytes).map_err(DocError::revision)
+    }
+}
+
+impl TryFrom<Revision> for Vec<u8> {
+    type Error = DocError;
+
+    fn try_from(revision: Revision) -> Result<Self, Self::Error> {
+        bincode::serialize(&revision).map_err(DocError::revision)
+    }
+}
+
+/// Identifies a remote, not-yet-applied revision pushed from the server.
+#[derive(Debug, Clone)]
+pub struct RemoteRevision {
+    pub doc_id: String,
+    pub bytes: Vec<u8>,
+}
@@ -0,0 +1,57 @@
+use std::fmt;
+
+pub type DocResult<T> = std::result::Result<T, DocError>;
+
+/// Layered so callers (in particular the sync worker) can decide retry vs.
+/// abort per category instead of pattern-matching an opaque string: retry
+/// `Transport`, surface `Transform` conflicts to the user, fail fast on
+/// `Revision`/`Persistence` corruption.
+#[derive(Debug, Clone)]
+pub enum DocError {
+    /// An OT `compose`/`transform` call failed - usually a conflicting edit.
+    Transform(String),
+    /// A `ConnectionPool`/SQL failure while reading or writing the doc tables.
+    Persistence(String),
+    /// A `Revision` or `Delta` failed to decode from its wire bytes.
+    Revision(String),
+    /// The sync worker failed to send to, or lost, the server stream.
+    Transport(String),
+}
+
+impl DocError {
+    pub fn transform<T: fmt::Debug>(e: T) -> Self { DocError::Transform(format!("{:?}", e)) }
+
+    pub fn persistence<T: fmt::Debug>(e: T) -> Self { DocError::Persistence(format!("{:?}", e)) }
+
+    pub fn revision<T: fmt::Debug>(e: T) -> Self { DocError::Revision(format!("{:?}", e)) }
+
+    pub fn transport<T: fmt::Debug>(e: T) -> Self { DocError::Transport(format!("{:?}", e)) }
+
+    /// True for categories where retrying the same operation later might
+    /// succeed (a dropped connection), as opposed to ones where it won't
+    /// (corrupt bytes, a conflicting transform).
+    pub fn is_retryable(&self) -> bool { matches!(self, DocError::Transport(_)) }
+}
+
+impl fmt::Display for DocError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DocError::Transform(msg) => write!(f, "transform error: {}", msg),
+            DocError::Persistence(msg) => write!(f, "persistence error: {}", msg),
+            DocError::Revision(msg) => write!(f, "revision error: {}", msg),
+            DocError::Transport(msg) => write!(f, "transport error: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for DocError {}
+
+/// Kept for call sites that don't yet have enough context to pick a more
+/// specific category; prefer `DocError::transform`/`persistence`/`revision`/
+/// `transport` directly when the failure's origin is known.
+pub fn internal_error<T>(e: T) -> DocError
+where
+    T: std::fmt::Debug,
+{
+    DocError::Persistence(format!("{:?}", e))
+}
@@ -0,0 +1,15 @@
+table! {
+    doc_table (id) {
+        id -> Text,
+        data -> Text,
+        rev_id -> BigInt,
+    }
+}
+
+table! {
+    rev_log_table (doc_id, rev_id) {
+        doc_id -> Text,
+        rev_id -> BigInt,
+        delta_data -> Binary,
+    }
+}
@@ -0,0 +1,134 @@
+use flowy_ot::core::{Delta, Interval, Operation};
+use std::collections::HashMap;
+use tokio::sync::broadcast;
+
+pub type PeerId = String;
+
+/// A peer's caret/selection, broadcast so remote carets stay anchored as
+/// text changes around them.
+#[derive(Debug, Clone)]
+pub struct CursorPosition {
+    pub peer_id: PeerId,
+    pub interval: Interval,
+}
+
+const CURSOR_BROADCAST_CAPACITY: usize = 100;
+
+/// Tracks every peer's caret/selection; `transform` must run on every
+/// applied delta to keep them anchored to the same logical text.
+pub struct CursorController {
+    cursors: HashMap<PeerId, Interval>,
+    notifier: broadcast::Sender<CursorPosition>,
+}
+
+impl CursorController {
+    pub fn new() -> Self {
+        let (notifier, _) = broadcast::channel(CURSOR_BROADCAST_CAPACITY);
+        Self {
+            cursors: HashMap::new(),
+            notifier,
+        }
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<CursorPosition> { self.notifier.subscribe() }
+
+    /// Records `peer_id`'s caret/selection and notifies subscribers.
+    pub fn move_cursor(&mut self, peer_id: PeerId, interval: Interval) {
+        self.cursors.insert(peer_id.clone(), interval);
+        let _ = self.notifier.send(CursorPosition { peer_id, interval });
+    }
+
+    /// Shifts every stored cursor through `delta`, using the same
+    /// operation-transform semantics the editor uses for text: a retain
+    /// before the cursor leaves it untouched, an insert at or before the
+    /// cursor pushes it right, and a delete spanning it clamps it to the
+    /// point of deletion.
+    pub fn transform(&mut self, delta: &Delta) {
+        if self.cursors.is_empty() {
+            return;
+        }
+        let peer_ids: Vec<PeerId> = self.cursors.keys().cloned().collect();
+        for peer_id in peer_ids {
+            let interval = self.cursors[&peer_id];
+            let start = transform_index(interval.start, delta);
+            let end = transform_index(interval.end, delta);
+            let transformed = Interval::new(start, end);
+            self.cursors.insert(peer_id.clone(), transformed);
+            let _ = self.notifier.send(CursorPosition {
+                peer_id,
+                interval: transformed,
+            });
+        }
+    }
+}
+
+/// Transforms a single document index through `delta`, mirroring the
+/// transform-position algorithm used for OT text: an insert occurring at or
+/// before `index` shifts it right by the insert's length; a delete
+/// overlapping `index` clamps it to the start of the deleted span.
+fn transform_index(index: usize, delta: &Delta) -> usize {
+    let mut offset = 0;
+    let mut index = index;
+    for op in delta.ops.iter() {
+        if offset > index {
+            break;
+        }
+        match op {
+            Operation::Retain(retain) => offset += retain.len(),
+            Operation::Insert(insert) => {
+                index += insert.len();
+                offset += insert.len();
+            },
+            Operation::Delete(len) => {
+                let removed = (*len).min(index.saturating_sub(offset));
+                index -= removed;
+            },
+        }
+    }
+    index
+}
+
+#[cfg(test)]
+mod tests {
+    use super::transform_index;
+    use flowy_ot::core::Delta;
+
+    #[test]
+    fn retain_before_cursor_leaves_it_untouched() {
+        let mut delta = Delta::new();
+        delta.retain(5, None);
+        assert_eq!(transform_index(10, &delta), 10);
+    }
+
+    #[test]
+    fn insert_before_cursor_pushes_it_right() {
+        let mut delta = Delta::new();
+        delta.retain(5, None);
+        delta.insert("hello", None);
+        assert_eq!(transform_index(10, &delta), 15);
+    }
+
+    #[test]
+    fn insert_after_cursor_leaves_it_untouched() {
+        let mut delta = Delta::new();
+        delta.retain(20, None);
+        delta.insert("hello", None);
+        assert_eq!(transform_index(10, &delta), 10);
+    }
+
+    #[test]
+    fn delete_spanning_cursor_clamps_it_to_deletion_start() {
+        let mut delta = Delta::new();
+        delta.retain(5, None);
+        delta.delete(10);
+        assert_eq!(transform_index(10, &delta), 5);
+    }
+
+    #[test]
+    fn delete_entirely_before_cursor_shifts_it_left() {
+        let mut delta = Delta::new();
+        delta.retain(2, None);
+        delta.delete(3);
+        assert_eq!(transform_index(10, &delta), 7);
+    }
+}
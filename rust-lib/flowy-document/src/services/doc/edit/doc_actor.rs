@@ -1,44 +1,128 @@
 use crate::{
     entities::doc::{RevId, Revision},
-    errors::{internal_error, DocResult},
+    errors::{DocError, DocResult},
     services::doc::{
         edit::{
-            message::{DocumentMsg, TransformDeltas},
+            cursor_controller::CursorController,
+            message::{DocumentChange, DocumentMsg, EditOp, TransformDeltas},
+            sync_worker::{DocumentSyncWorker, RevisionServer},
             DocId,
         },
         Document,
     },
-    sql_tables::{DocTableChangeset, DocTableSql},
+    sql_tables::{DocTableChangeset, DocTableSql, RevLogRow, RevLogSql},
 };
 use async_stream::stream;
 use flowy_database::ConnectionPool;
 use flowy_ot::core::{Delta, OperationTransformable};
 use futures::stream::StreamExt;
-use std::{convert::TryFrom, sync::Arc};
-use tokio::sync::{mpsc, RwLock};
+use std::{
+    convert::TryFrom,
+    sync::{
+        atomic::{AtomicI64, AtomicUsize, Ordering},
+        Arc,
+    },
+};
+use tokio::sync::{broadcast, mpsc, RwLock};
+
+/// Bounded broadcast capacity for `changes` (see `broadcast_change`).
+const CHANGE_BROADCAST_CAPACITY: usize = 50;
+
+/// Take a fresh snapshot after this many revisions have been appended to the
+/// log since the last one, even if the byte threshold below hasn't been hit.
+const SNAPSHOT_REV_INTERVAL: i64 = 100;
+/// ...or once the un-snapshotted deltas add up to this many bytes, whichever
+/// comes first - keeps a single giant paste from living only in the log.
+const SNAPSHOT_BYTES_THRESHOLD: usize = 64 * 1024;
+
+/// What `DocumentActor::load` restored: the reconstructed head, the rev_id it
+/// was reconstructed at, and how much of the log tail was replayed to get
+/// there - the latter seeds `DocumentActor`'s snapshot-compaction counters so
+/// a doc that restarts often still compacts on schedule instead of the
+/// thresholds resetting to zero on every restart.
+pub struct LoadedDocument {
+    pub delta: Delta,
+    pub rev_id: RevId,
+    pub revs_since_snapshot: i64,
+    pub bytes_since_snapshot: usize,
+}
 
 pub struct DocumentActor {
     doc_id: DocId,
     document: Arc<RwLock<Document>>,
     pool: Arc<ConnectionPool>,
+    sync_worker: DocumentSyncWorker,
+    cursors: RwLock<CursorController>,
+    changes: broadcast::Sender<DocumentChange>,
+    self_sender: mpsc::UnboundedSender<DocumentMsg>,
     receiver: Option<mpsc::UnboundedReceiver<DocumentMsg>>,
+    /// Revisions and bytes appended to the log since the last snapshot;
+    /// reset whenever `save_to_disk` decides to compact.
+    revs_since_snapshot: AtomicI64,
+    bytes_since_snapshot: AtomicUsize,
 }
 
 impl DocumentActor {
     pub fn new(
         doc_id: &str,
-        delta: Delta,
+        loaded: LoadedDocument,
         pool: Arc<ConnectionPool>,
+        server: Arc<dyn RevisionServer>,
+        self_sender: mpsc::UnboundedSender<DocumentMsg>,
         receiver: mpsc::UnboundedReceiver<DocumentMsg>,
     ) -> Self {
         let doc_id = doc_id.to_string();
-        let document = Arc::new(RwLock::new(Document::from_delta(delta)));
+        let document = Arc::new(RwLock::new(Document::from_delta(loaded.delta)));
+        let sync_worker = DocumentSyncWorker::new(&doc_id, loaded.rev_id, server);
+        sync_worker.listen(self_sender.clone());
+        let (changes, _) = broadcast::channel(CHANGE_BROADCAST_CAPACITY);
         Self {
             doc_id,
             document,
             pool,
+            sync_worker,
+            cursors: RwLock::new(CursorController::new()),
+            changes,
+            self_sender,
             receiver: Some(receiver),
+            revs_since_snapshot: AtomicI64::new(loaded.revs_since_snapshot),
+            bytes_since_snapshot: AtomicUsize::new(loaded.bytes_since_snapshot),
+        }
+    }
+
+    /// Restores the latest snapshot for `doc_id` (or an empty document if
+    /// none exists yet) and replays the log tail recorded after it via
+    /// `Delta::compose`, reconstructing the head without ever reading the
+    /// full, unbounded history.
+    pub fn load(doc_id: &str, pool: &Arc<ConnectionPool>) -> DocResult<LoadedDocument> {
+        let conn = pool.get().map_err(DocError::persistence)?;
+        let doc_sql = DocTableSql {};
+        let rev_log_sql = RevLogSql {};
+
+        let (mut delta, base_rev_id) = match doc_sql.read_doc_table(doc_id, &*conn)? {
+            Some(snapshot) => (
+                Delta::from_json(&snapshot.data).map_err(DocError::revision)?,
+                snapshot.rev_id,
+            ),
+            None => (Delta::new(), 0),
+        };
+
+        let mut rev_id = base_rev_id;
+        let mut revs_since_snapshot = 0;
+        let mut bytes_since_snapshot = 0;
+        for row in rev_log_sql.read_tail(doc_id, base_rev_id, &*conn)? {
+            let tail_delta = Delta::from_bytes(&row.delta_data).map_err(DocError::revision)?;
+            delta = delta.compose(&tail_delta).map_err(DocError::transform)?;
+            rev_id = row.rev_id;
+            revs_since_snapshot += 1;
+            bytes_since_snapshot += row.delta_data.len();
         }
+        Ok(LoadedDocument {
+            delta,
+            rev_id: rev_id.into(),
+            revs_since_snapshot,
+            bytes_since_snapshot,
+        })
     }
 
     pub async fn run(mut self) {
@@ -68,23 +152,21 @@ impl DocumentActor {
                 let _ = ret.send(result);
             },
             DocumentMsg::RemoteRevision { bytes, ret } => {
-                let revision = Revision::try_from(bytes)?;
-                let delta = Delta::from_bytes(&revision.delta_data)?;
-                let rev_id: RevId = revision.rev_id.into();
-                let (server_prime, client_prime) = self.document.read().await.delta().transform(&delta)?;
-                let transform_delta = TransformDeltas {
-                    client_prime,
-                    server_prime,
-                    server_rev_id: rev_id,
-                };
-                let _ = ret.send(Ok(transform_delta));
+                let result = self.handle_remote_revision(bytes).await;
+                let _ = ret.send(result);
             },
             DocumentMsg::Insert { index, data, ret } => {
-                let delta = self.document.write().await.insert(index, data);
-                let _ = ret.send(delta);
+                let result = self.document.write().await.insert(index, data);
+                if let Ok(delta) = &result {
+                    self.apply_local_change(delta).await;
+                }
+                let _ = ret.send(result);
             },
             DocumentMsg::Delete { interval, ret } => {
                 let result = self.document.write().await.delete(interval);
+                if let Ok(delta) = &result {
+                    self.apply_local_change(delta).await;
+                }
                 let _ = ret.send(result);
             },
             DocumentMsg::Format {
@@ -93,10 +175,16 @@ impl DocumentActor {
                 ret,
             } => {
                 let result = self.document.write().await.format(interval, attribute);
+                if let Ok(delta) = &result {
+                    self.apply_local_change(delta).await;
+                }
                 let _ = ret.send(result);
             },
             DocumentMsg::Replace { interval, data, ret } => {
                 let result = self.document.write().await.replace(interval, data);
+                if let Ok(delta) = &result {
+                    self.apply_local_change(delta).await;
+                }
                 let _ = ret.send(result);
             },
             DocumentMsg::CanUndo { ret } => {
@@ -107,24 +195,83 @@ impl DocumentActor {
             },
             DocumentMsg::Undo { ret } => {
                 let result = self.document.write().await.undo();
+                if let Ok(delta) = &result {
+                    self.apply_local_change(delta).await;
+                }
                 let _ = ret.send(result);
             },
             DocumentMsg::Redo { ret } => {
                 let result = self.document.write().await.redo();
+                if let Ok(delta) = &result {
+                    self.apply_local_change(delta).await;
+                }
                 let _ = ret.send(result);
             },
             DocumentMsg::Doc { ret } => {
                 let data = self.document.read().await.to_json();
                 let _ = ret.send(Ok(data));
             },
-            DocumentMsg::SaveDocument { rev_id, ret } => {
-                let result = self.save_to_disk(rev_id).await;
+            DocumentMsg::SaveDocument { rev_id, delta } => {
+                self.save_to_disk(rev_id, delta).await?;
+            },
+            DocumentMsg::PushToServer {
+                base_rev_id,
+                rev_id,
+                delta,
+            } => {
+                self.sync_worker.push_local_delta(base_rev_id, rev_id, delta).await;
+            },
+            DocumentMsg::AckReceived { rev_id } => {
+                self.sync_worker.ack(rev_id).await;
+            },
+            DocumentMsg::CursorMove { peer_id, interval } => {
+                self.cursors.write().await.move_cursor(peer_id, interval);
+            },
+            DocumentMsg::SubscribeCursors { ret } => {
+                let _ = ret.send(self.cursors.read().await.subscribe());
+            },
+            DocumentMsg::Transact { ops, ret } => {
+                let result = self.transact(ops).await;
                 let _ = ret.send(result);
             },
+            DocumentMsg::Subscribe { ret } => {
+                let _ = ret.send(self.changes.subscribe());
+            },
         }
         Ok(())
     }
 
+    /// Applies every op in `ops` to the document under a single write lock,
+    /// composing the deltas they return into one combined `Delta` so the
+    /// sync worker and change subscribers only ever see the merged change,
+    /// never the individual steps. `Document` has no batch/transaction
+    /// primitive, so each op still records its own undo-stack entry.
+    async fn transact(&self, ops: Vec<EditOp>) -> DocResult<Delta> {
+        let mut document = self.document.write().await;
+        let mut combined: Option<Delta> = None;
+        for op in ops {
+            let delta = match op {
+                EditOp::Insert { index, data } => document.insert(index, data)?,
+                EditOp::Delete { interval } => document.delete(interval)?,
+                EditOp::Format { interval, attribute } => document.format(interval, attribute)?,
+                EditOp::Replace { interval, data } => document.replace(interval, data)?,
+            };
+            combined = Some(match combined {
+                Some(acc) => acc.compose(&delta).map_err(DocError::transform)?,
+                None => delta,
+            });
+        }
+        drop(document);
+        let delta = combined.unwrap_or_else(Delta::new);
+        log::debug!(
+            "Client transact push delta: {}. result: {}",
+            delta.to_json(),
+            self.document.read().await.to_json()
+        );
+        self.apply_local_change(&delta).await;
+        Ok(delta)
+    }
+
     async fn compose_delta(&self, delta: Delta) -> DocResult<()> {
         let result = self.document.write().await.compose_delta(&delta);
         log::debug!(
@@ -132,36 +279,111 @@ impl DocumentActor {
             delta.to_json(),
             self.document.read().await.to_json()
         );
+        if result.is_ok() {
+            self.apply_local_change(&delta).await;
+        }
         result
     }
 
-    #[tracing::instrument(level = "debug", skip(self, rev_id), err)]
-    async fn save_to_disk(&self, rev_id: RevId) -> DocResult<()> {
+    /// Shared tail for every handler that just produced a locally-authored
+    /// `Delta`: transforms stored cursors through it, broadcasts it as a
+    /// `DocumentChange`, and hands it to the sync worker to push. Every
+    /// mutating `DocumentMsg` - the plain per-op API as well as `Delta`/
+    /// `Transact` - routes through this so none of them silently diverge
+    /// from cursor-tracking, change subscriptions, or server sync.
+    async fn apply_local_change(&self, delta: &Delta) {
+        let base_rev_id = self.sync_worker.acked_rev_id();
+        self.cursors.write().await.transform(delta);
+        // The rev_id the worker will actually queue/send must come from its
+        // own counter, not be guessed from `acked_rev_id() + 1`, which only
+        // holds with zero revisions in flight.
+        let rev_id = self.sync_worker.assign_rev_id();
+        self.broadcast_change(rev_id, delta.clone());
+        let _ = self.self_sender.send(DocumentMsg::SaveDocument {
+            rev_id,
+            delta: delta.clone(),
+        });
+        let _ = self.self_sender.send(DocumentMsg::PushToServer {
+            base_rev_id,
+            rev_id,
+            delta: delta.clone(),
+        });
+    }
+
+    /// Publishes a `DocumentChange` to every subscriber. A subscriber that
+    /// falls more than `CHANGE_BROADCAST_CAPACITY` changes behind gets a
+    /// `Lagged` error on its next `recv` rather than stalling us.
+    fn broadcast_change(&self, rev_id: RevId, delta: Delta) {
+        let _ = self.changes.send(DocumentChange { rev_id, delta });
+    }
+
+    /// A revision arrived from the server while we may still have un-acked
+    /// local edits in flight. Transform it against the current document to
+    /// split into `(server_prime, client_prime)`, apply `server_prime`
+    /// locally, and let the sync worker rebase its pending queue against the
+    /// incoming delta before re-sending.
+    async fn handle_remote_revision(&self, bytes: Vec<u8>) -> DocResult<TransformDeltas> {
+        let revision = Revision::try_from(bytes)?;
+        let delta = Delta::from_bytes(&revision.delta_data).map_err(DocError::revision)?;
+        let rev_id: RevId = revision.rev_id.into();
+        let (server_prime, client_prime) = self
+            .document
+            .read()
+            .await
+            .delta()
+            .transform(&delta)
+            .map_err(DocError::transform)?;
+        self.document.write().await.compose_delta(&server_prime)?;
+        self.cursors.write().await.transform(&server_prime);
+        self.broadcast_change(rev_id, server_prime.clone());
+        let _ = self.self_sender.send(DocumentMsg::SaveDocument {
+            rev_id,
+            delta: server_prime.clone(),
+        });
+        self.sync_worker.reconcile_remote_revision(&revision, &delta).await?;
+        Ok(TransformDeltas {
+            client_prime,
+            server_prime,
+            server_rev_id: rev_id,
+        })
+    }
+
+    /// Appends `delta` to the append-only revision log. A full snapshot (and
+    /// the compaction that prunes the log up to it) only happens every
+    /// `SNAPSHOT_REV_INTERVAL` revisions or `SNAPSHOT_BYTES_THRESHOLD` bytes,
+    /// whichever comes first - most calls are a single cheap log append.
+    #[tracing::instrument(level = "debug", skip(self, rev_id, delta), err)]
+    async fn save_to_disk(&self, rev_id: RevId, delta: Delta) -> DocResult<()> {
+        let conn = self.pool.get().map_err(DocError::persistence)?;
+        let rev_log_sql = RevLogSql {};
+        let delta_data = delta.to_bytes();
+        let delta_len = delta_data.len();
+        rev_log_sql.append_revision(
+            RevLogRow {
+                doc_id: self.doc_id.clone(),
+                rev_id: rev_id.into(),
+                delta_data,
+            },
+            &*conn,
+        )?;
+
+        let revs = self.revs_since_snapshot.fetch_add(1, Ordering::SeqCst) + 1;
+        let bytes = self.bytes_since_snapshot.fetch_add(delta_len, Ordering::SeqCst) + delta_len;
+        if revs < SNAPSHOT_REV_INTERVAL && bytes < SNAPSHOT_BYTES_THRESHOLD {
+            return Ok(());
+        }
+
         let data = self.document.read().await.to_json();
         let changeset = DocTableChangeset {
             id: self.doc_id.clone(),
             data,
             rev_id: rev_id.into(),
         };
-        let sql = DocTableSql {};
-        let conn = self.pool.get().map_err(internal_error)?;
-        let _ = sql.update_doc_table(changeset, &*conn)?;
+        let doc_sql = DocTableSql {};
+        doc_sql.update_doc_table(changeset, &*conn)?;
+        rev_log_sql.prune_before(&self.doc_id, rev_id.into(), &*conn)?;
+        self.revs_since_snapshot.store(0, Ordering::SeqCst);
+        self.bytes_since_snapshot.store(0, Ordering::SeqCst);
         Ok(())
     }
-}
-
-// #[tracing::instrument(level = "debug", skip(self, params), err)]
-// fn update_doc_on_server(&self, params: UpdateDocParams) -> Result<(),
-//     DocError> {     let token = self.user.token()?;
-//     let server = self.server.clone();
-//     tokio::spawn(async move {
-//         match server.update_doc(&token, params).await {
-//             Ok(_) => {},
-//             Err(e) => {
-//                 // TODO: retry?
-//                 log::error!("Update doc failed: {}", e);
-//             },
-//         }
-//     });
-//     Ok(())
-// }
\ No newline at end of file
+}
\ No newline at end of file
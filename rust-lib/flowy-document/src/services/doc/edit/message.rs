@@ -0,0 +1,127 @@
+use crate::entities::doc::RevId;
+use crate::errors::DocResult;
+use crate::services::doc::edit::cursor_controller::{CursorPosition, PeerId};
+use flowy_ot::core::{Delta, Interval};
+use tokio::sync::{broadcast, oneshot};
+
+pub(crate) type Ret<T> = oneshot::Sender<DocResult<T>>;
+
+/// Emitted on the `DocumentActor`'s change broadcast every time a delta
+/// mutates the document, whether from a local edit or a reconciled remote
+/// revision. `rev_id` is the revision the delta produced.
+#[derive(Debug, Clone)]
+pub struct DocumentChange {
+    pub rev_id: RevId,
+    pub delta: Delta,
+}
+
+/// The result of transforming the local document against a concurrently
+/// arrived `RemoteRevision`. `server_prime` is the half that has already been
+/// composed into the local document; `client_prime` is the other half of the
+/// split, returned as-is.
+pub struct TransformDeltas {
+    pub client_prime: Delta,
+    pub server_prime: Delta,
+    pub server_rev_id: RevId,
+}
+
+/// One edit within a `DocumentMsg::Transact` batch. Mirrors the standalone
+/// per-op `DocumentMsg` variants, but `DocumentActor::transact` composes all
+/// of them into a single `Delta` instead of emitting one delta per op. Each
+/// op still records its own undo-stack entry.
+pub enum EditOp {
+    Insert { index: usize, data: String },
+    Delete { interval: Interval },
+    Format { interval: Interval, attribute: String },
+    Replace { interval: Interval, data: String },
+}
+
+pub enum DocumentMsg {
+    Delta {
+        delta: Delta,
+        ret: Ret<()>,
+    },
+    RemoteRevision {
+        bytes: Vec<u8>,
+        ret: Ret<TransformDeltas>,
+    },
+    Insert {
+        index: usize,
+        data: String,
+        ret: Ret<Delta>,
+    },
+    Delete {
+        interval: Interval,
+        ret: Ret<Delta>,
+    },
+    Format {
+        interval: Interval,
+        attribute: String,
+        ret: Ret<Delta>,
+    },
+    Replace {
+        interval: Interval,
+        data: String,
+        ret: Ret<Delta>,
+    },
+    CanUndo {
+        ret: oneshot::Sender<bool>,
+    },
+    CanRedo {
+        ret: oneshot::Sender<bool>,
+    },
+    Undo {
+        ret: Ret<Delta>,
+    },
+    Redo {
+        ret: Ret<Delta>,
+    },
+    Doc {
+        ret: Ret<String>,
+    },
+    /// Self-dispatched alongside `PushToServer` so `delta` (the revision that
+    /// produced `rev_id`) is appended to the append-only revision log, taking
+    /// a full snapshot (and pruning the log up to it) only every
+    /// `SNAPSHOT_REV_INTERVAL` revisions or once the un-snapshotted delta
+    /// bytes cross `SNAPSHOT_BYTES_THRESHOLD`.
+    SaveDocument {
+        rev_id: RevId,
+        delta: Delta,
+    },
+    /// Self-dispatched after a local edit is composed so the network push
+    /// (and its retry/backoff) runs as a separate turn of the message loop
+    /// instead of blocking the edit that triggered it.
+    PushToServer {
+        base_rev_id: RevId,
+        rev_id: RevId,
+        delta: Delta,
+    },
+    /// The server acknowledged `rev_id`; drop every queued revision up to and
+    /// including it and advance the last-acked marker.
+    AckReceived {
+        rev_id: RevId,
+    },
+    /// A peer moved their caret/selection; broadcast the new position to
+    /// every subscriber after recording it.
+    CursorMove {
+        peer_id: PeerId,
+        interval: Interval,
+    },
+    /// Subscribe to every peer's cursor position as it changes.
+    SubscribeCursors {
+        ret: oneshot::Sender<broadcast::Receiver<CursorPosition>>,
+    },
+    /// Apply a batch of edits atomically: one write lock, one combined
+    /// delta, and (so the sync worker only sees the merged change) one
+    /// outgoing revision. Undo is not batched: each op records its own
+    /// undo-stack entry.
+    Transact {
+        ops: Vec<EditOp>,
+        ret: Ret<Delta>,
+    },
+    /// Subscribe to every document mutation as a `DocumentChange` stream,
+    /// instead of polling `DocumentMsg::Doc` for the full JSON on each edit.
+    Subscribe {
+        ret: oneshot::Sender<broadcast::Receiver<DocumentChange>>,
+    },
+}
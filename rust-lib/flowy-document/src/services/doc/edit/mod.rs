@@ -0,0 +1,11 @@
+mod cursor_controller;
+mod doc_actor;
+mod message;
+mod sync_worker;
+
+pub use cursor_controller::{CursorPosition, PeerId};
+pub use doc_actor::*;
+pub use message::*;
+pub use sync_worker::RevisionServer;
+
+pub type DocId = String;
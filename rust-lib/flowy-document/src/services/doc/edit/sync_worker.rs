@@ -0,0 +1,201 @@
+use crate::{
+    entities::doc::{RevId, Revision},
+    errors::DocResult,
+    services::doc::edit::{DocId, DocumentMsg},
+};
+use flowy_ot::core::{Delta, OperationTransformable};
+use std::{
+    collections::VecDeque,
+    sync::atomic::{AtomicI64, Ordering},
+    time::Duration,
+};
+use tokio::sync::{mpsc, RwLock};
+
+/// Abstraction over the bidirectional channel to the collaboration server.
+/// `DocumentSyncWorker` is transport-agnostic; a websocket (or, in tests, an
+/// in-memory) implementation plugs in here. `listen` owns the receive side
+/// and feeds incoming acks/remote revisions back into the document actor's
+/// own message loop as `AckReceived`/`RemoteRevision`.
+#[async_trait::async_trait]
+pub trait RevisionServer: Send + Sync {
+    /// Implementations should report connection/stream failures as
+    /// `DocError::Transport` so `send_with_retry` (and any caller further up)
+    /// can tell a retryable hiccup from a hard failure.
+    async fn send_revision(&self, revision: &Revision) -> DocResult<()>;
+
+    async fn listen(&self, doc_id: DocId, actor_sender: mpsc::UnboundedSender<DocumentMsg>);
+}
+
+/// Owns the client side of revision-sync for a single document: assigns
+/// monotonically increasing `rev_id`s to outgoing deltas, tracks which of
+/// them the server has not yet acked, and reconciles remote revisions that
+/// race with a pending local edit using the same OT `transform` the editor
+/// uses for local composition. Driven entirely by `DocumentActor::handle_message`
+/// so pushes/acks/rebases never interleave with a concurrent edit.
+pub struct DocumentSyncWorker {
+    doc_id: DocId,
+    server: std::sync::Arc<dyn RevisionServer>,
+    next_rev_id: AtomicI64,
+    acked_rev_id: AtomicI64,
+    pending: RwLock<VecDeque<Revision>>,
+}
+
+impl DocumentSyncWorker {
+    pub fn new(doc_id: &str, rev_id: RevId, server: std::sync::Arc<dyn RevisionServer>) -> Self {
+        Self {
+            doc_id: doc_id.to_owned(),
+            server,
+            next_rev_id: AtomicI64::new(rev_id.into()),
+            acked_rev_id: AtomicI64::new(rev_id.into()),
+            pending: RwLock::new(VecDeque::new()),
+        }
+    }
+
+    /// Spawns the background task that listens on the server's bidirectional
+    /// stream and forwards what it hears back into the actor's own mpsc
+    /// channel, so incoming acks and remote revisions are handled by the
+    /// same message loop as local edits.
+    pub fn listen(&self, actor_sender: mpsc::UnboundedSender<DocumentMsg>) {
+        let server = self.server.clone();
+        let doc_id = self.doc_id.clone();
+        tokio::spawn(async move { server.listen(doc_id, actor_sender).await });
+    }
+
+    pub fn acked_rev_id(&self) -> RevId { self.acked_rev_id.load(Ordering::SeqCst).into() }
+
+    /// Atomically assigns the next rev_id. Called synchronously by the actor
+    /// (which only ever handles one message at a time) so the rev_id it
+    /// broadcasts/persists is the exact one the worker will queue and send -
+    /// never a value guessed ahead of time from `acked_rev_id() + 1`, which
+    /// only holds with zero revisions in flight.
+    pub fn assign_rev_id(&self) -> RevId { (self.next_rev_id.fetch_add(1, Ordering::SeqCst) + 1).into() }
+
+    /// Wraps `delta` in a `Revision` at the already-assigned `rev_id` (see
+    /// `assign_rev_id`) based on `base_rev_id`, queues it as un-acked, and
+    /// spawns the retry/backoff send as its own task so a flaky connection
+    /// never stalls the actor's message loop.
+    pub async fn push_local_delta(&self, base_rev_id: RevId, rev_id: RevId, delta: Delta) {
+        let revision = Revision::new(&self.doc_id, base_rev_id.into(), rev_id.into(), delta.to_bytes());
+        self.pending.write().await.push_back(revision.clone());
+        tokio::spawn(Self::send_with_retry(self.server.clone(), revision));
+    }
+
+    /// Pops the acked revision (and anything older, which the server must
+    /// have folded in already) from the pending queue and advances the
+    /// watermark used as the base for the next local push.
+    pub async fn ack(&self, rev_id: RevId) {
+        let rev_id: i64 = rev_id.into();
+        self.pending.write().await.retain(|revision| revision.rev_id > rev_id);
+        self.acked_rev_id.fetch_max(rev_id, Ordering::SeqCst);
+    }
+
+    /// A concurrent server revision arrived before our pending edits were
+    /// acked. The caller has already applied its own `transform` split of
+    /// the remote delta locally; this rebases every still-pending local
+    /// revision against the incoming delta so future acks stay consistent,
+    /// and re-sends the rebased revisions in place of the originals.
+    pub async fn reconcile_remote_revision(&self, remote: &Revision, incoming: &Delta) -> DocResult<()> {
+        let mut pending = self.pending.write().await;
+        let mut rebased = VecDeque::with_capacity(pending.len());
+        // Each queued revision must be transformed against `incoming` as
+        // rebased by every queued revision before it, not against the
+        // server's original delta every time - otherwise the second and
+        // later queued edits land at the position they'd have had if they
+        // were the only pending edit, which is wrong as soon as two or more
+        // are outstanding.
+        let mut incoming = incoming.clone();
+        for queued in pending.drain(..) {
+            let local = Delta::from_bytes(&queued.delta_data).map_err(crate::errors::DocError::revision)?;
+            let (incoming_prime, client_prime) = incoming.transform(&local).map_err(crate::errors::DocError::transform)?;
+            incoming = incoming_prime;
+            rebased.push_back(Revision::new(
+                &self.doc_id,
+                remote.rev_id,
+                queued.rev_id,
+                client_prime.to_bytes(),
+            ));
+        }
+        *pending = rebased;
+        self.acked_rev_id.fetch_max(remote.rev_id, Ordering::SeqCst);
+        // A remote edit can jump the rev_id sequence ahead of our own
+        // counter (other peers assign rev_ids too); the next local edit must
+        // still come out strictly higher, or the log's rev_id ordering -
+        // `RevLogSql::read_tail`/`prune_before` both key off it - breaks.
+        self.next_rev_id.fetch_max(remote.rev_id, Ordering::SeqCst);
+
+        for revision in pending.iter().cloned() {
+            tokio::spawn(Self::send_with_retry(self.server.clone(), revision));
+        }
+        Ok(())
+    }
+
+    /// Retries only transport hiccups. A `Transform`/`Revision`/`Persistence`
+    /// error means the revision itself is bad or a conflict needs resolving
+    /// upstream - retrying it unchanged would just fail the same way four
+    /// more times, so those abort immediately instead. Takes `server` by
+    /// value (not `&self`) so callers can spawn it as an independent task
+    /// instead of blocking the actor's message loop on the backoff.
+    async fn send_with_retry(server: std::sync::Arc<dyn RevisionServer>, revision: Revision) {
+        let mut backoff = Duration::from_millis(200);
+        for attempt in 0..5 {
+            match server.send_revision(&revision).await {
+                Ok(_) => return,
+                Err(e) if e.is_retryable() => {
+                    log::error!("Push revision {} failed (attempt {}): {:?}", revision.rev_id, attempt, e);
+                    tokio::time::sleep(backoff).await;
+                    backoff *= 2;
+                },
+                Err(e) => {
+                    log::error!("Push revision {} failed with a non-retryable error: {:?}", revision.rev_id, e);
+                    return;
+                },
+            }
+        }
+        log::error!("Giving up pushing revision {} after retries", revision.rev_id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entities::doc::Revision;
+    use async_trait::async_trait;
+    use std::sync::Arc;
+    use tokio::sync::mpsc::unbounded_channel;
+
+    struct NoopServer;
+
+    #[async_trait]
+    impl RevisionServer for NoopServer {
+        async fn send_revision(&self, _revision: &Revision) -> DocResult<()> { Ok(()) }
+
+        async fn listen(&self, _doc_id: DocId, _actor_sender: mpsc::UnboundedSender<DocumentMsg>) {}
+    }
+
+    #[tokio::test]
+    async fn reconcile_rebases_each_queued_revision_against_the_previous_ones_output() {
+        let worker = DocumentSyncWorker::new("doc-1", 0.into(), Arc::new(NoopServer));
+
+        // Two local edits queued back to back, both still un-acked.
+        let first = Delta::from_json(r#"[{"insert":"a"}]"#).unwrap();
+        let second = Delta::from_json(r#"[{"retain":1},{"insert":"b"}]"#).unwrap();
+        worker.push_local_delta(0.into(), 1.into(), first.clone()).await;
+        worker.push_local_delta(1.into(), 2.into(), second.clone()).await;
+
+        let remote = Delta::from_json(r#"[{"insert":"z"}]"#).unwrap();
+        let revision = Revision::new("doc-1", 0, 10, remote.to_bytes());
+        worker.reconcile_remote_revision(&revision, &remote).await.unwrap();
+
+        // Rebasing must thread the transformed delta through both queued
+        // revisions in order, not re-transform each one against the
+        // original `remote` delta.
+        let (remote_prime, first_prime) = remote.transform(&first).unwrap();
+        let (_, second_prime) = remote_prime.transform(&second).unwrap();
+
+        let pending = worker.pending.read().await;
+        assert_eq!(pending.len(), 2);
+        assert_eq!(Delta::from_bytes(&pending[0].delta_data).unwrap(), first_prime);
+        assert_eq!(Delta::from_bytes(&pending[1].delta_data).unwrap(), second_prime);
+        assert_eq!(worker.acked_rev_id(), 10.into());
+    }
+}
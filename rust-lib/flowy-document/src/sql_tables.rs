@@ -0,0 +1,84 @@
+use crate::{
+    errors::{DocError, DocResult},
+    schema::{doc_table, doc_table::dsl as doc_dsl, rev_log_table, rev_log_table::dsl as rev_log_dsl},
+};
+use diesel::prelude::*;
+use flowy_database::SqliteConnection;
+
+/// The latest materialized snapshot of a document: the full `to_json` blob
+/// plus the rev_id it was taken at. `RevLogSql` holds everything *after*
+/// `rev_id`; replaying that tail onto this snapshot reconstructs the head.
+#[derive(Queryable, Insertable, AsChangeset, Debug, Clone)]
+#[table_name = "doc_table"]
+pub struct DocTableChangeset {
+    pub id: String,
+    pub data: String,
+    pub rev_id: i64,
+}
+
+pub struct DocTableSql {}
+
+impl DocTableSql {
+    pub fn update_doc_table(&self, changeset: DocTableChangeset, conn: &SqliteConnection) -> DocResult<()> {
+        diesel::replace_into(doc_table::table)
+            .values(&changeset)
+            .execute(conn)
+            .map_err(DocError::persistence)?;
+        Ok(())
+    }
+
+    pub fn read_doc_table(&self, doc_id: &str, conn: &SqliteConnection) -> DocResult<Option<DocTableChangeset>> {
+        doc_dsl::doc_table
+            .filter(doc_dsl::id.eq(doc_id))
+            .first::<DocTableChangeset>(conn)
+            .optional()
+            .map_err(DocError::persistence)
+    }
+}
+
+/// One row of the append-only revision log: a single applied `Delta`, keyed
+/// by the rev_id it produced. Rows with `rev_id <= base_rev_id` of the most
+/// recent snapshot are safe to prune.
+#[derive(Queryable, Insertable, Debug, Clone)]
+#[table_name = "rev_log_table"]
+pub struct RevLogRow {
+    pub doc_id: String,
+    pub rev_id: i64,
+    pub delta_data: Vec<u8>,
+}
+
+pub struct RevLogSql {}
+
+impl RevLogSql {
+    pub fn append_revision(&self, row: RevLogRow, conn: &SqliteConnection) -> DocResult<()> {
+        diesel::insert_into(rev_log_table::table)
+            .values(&row)
+            .execute(conn)
+            .map_err(DocError::persistence)?;
+        Ok(())
+    }
+
+    /// Rows for `doc_id` with `rev_id > since_rev_id`, ordered by `rev_id`,
+    /// for replaying onto a snapshot taken at `since_rev_id`.
+    pub fn read_tail(&self, doc_id: &str, since_rev_id: i64, conn: &SqliteConnection) -> DocResult<Vec<RevLogRow>> {
+        rev_log_dsl::rev_log_table
+            .filter(rev_log_dsl::doc_id.eq(doc_id))
+            .filter(rev_log_dsl::rev_id.gt(since_rev_id))
+            .order(rev_log_dsl::rev_id.asc())
+            .load::<RevLogRow>(conn)
+            .map_err(DocError::persistence)
+    }
+
+    /// Called once a new snapshot based at `base_rev_id` has been written;
+    /// everything at or before it is already folded into the snapshot.
+    pub fn prune_before(&self, doc_id: &str, base_rev_id: i64, conn: &SqliteConnection) -> DocResult<()> {
+        diesel::delete(
+            rev_log_dsl::rev_log_table
+                .filter(rev_log_dsl::doc_id.eq(doc_id))
+                .filter(rev_log_dsl::rev_id.le(base_rev_id)),
+        )
+        .execute(conn)
+        .map_err(DocError::persistence)?;
+        Ok(())
+    }
+}